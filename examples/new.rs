@@ -1,7 +1,7 @@
-use futures::TryStreamExt;
-use tiberius::{AuthMethod, Client};
+use tiberius::AuthMethod;
+use tiberius_async_std::Client;
 
-#[tokio::main]
+#[async_std::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::init();
     let mut builder = Client::builder();
@@ -11,11 +11,11 @@ async fn main() -> anyhow::Result<()> {
     builder.authentication(AuthMethod::sql_server("SA", "<YourStrong@Passw0rd>"));
 
     let mut conn = builder.build().await?;
-    let stream = conn.query("SELECT 1", &[]).await?;
+    let rows = conn.query_first("SELECT 1", &[]).await?;
 
-    let rows: Vec<_> = stream.map_ok(|x| x.get::<_, i32>(0)).try_collect().await?;
-    assert_eq!(1i32, rows[0]);
-    dbg!(rows);
+    let value: i32 = rows[0].get(0).unwrap();
+    assert_eq!(1i32, value);
+    dbg!(value);
 
     Ok(())
-}
\ No newline at end of file
+}
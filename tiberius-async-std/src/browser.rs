@@ -0,0 +1,258 @@
+//! Resolution and enumeration of SQL Server instances via the SQL Browser
+//! service (the SSRP/MS-SQLR protocol), available on every platform since it
+//! only needs a plain UDP socket.
+
+use std::{net::SocketAddr, str, time};
+
+use async_std::{io, net::UdpSocket};
+use futures::TryFutureExt;
+
+const SQL_BROWSER_PORT: u16 = 1434;
+const RECV_TIMEOUT: time::Duration = time::Duration::from_millis(1000);
+
+/// A single SQL Server instance as reported by the SQL Browser service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlBrowserInstance {
+    /// The server name, if the browser reported one.
+    pub server: Option<String>,
+    /// The instance name, e.g. `SQLEXPRESS`.
+    pub instance: String,
+    /// The TCP port the instance listens on.
+    pub port: u16,
+    /// The instance's reported version string, if any.
+    pub version: Option<String>,
+    /// Whether the instance is part of a failover cluster.
+    pub is_clustered: bool,
+}
+
+/// Resolves a single instance name on `addr` to the TCP port it listens on.
+pub(crate) async fn resolve_instance_port(
+    addr: SocketAddr,
+    instance_name: &str,
+) -> tiberius::Result<SocketAddr> {
+    let browser_addr = SocketAddr::new(addr.ip(), SQL_BROWSER_PORT);
+    let msg = [&[0x04u8], instance_name.as_bytes()].concat();
+    let buf = send_and_recv(browser_addr, &msg).await?;
+    let instances = parse_response(&buf);
+
+    instances
+        .into_iter()
+        .find(|i| i.instance.eq_ignore_ascii_case(instance_name))
+        .map(|i| SocketAddr::new(addr.ip(), i.port))
+        .ok_or_else(|| {
+            tiberius::Error::Conversion(
+                format!("instance {} not found on the SQL Browser", instance_name).into(),
+            )
+        })
+}
+
+/// Enumerates every instance running on `host`'s SQL Browser service.
+///
+/// ```no_run
+/// # #[allow(unused)]
+/// # async fn foo() -> tiberius::Result<()> {
+/// for instance in tiberius_async_std::enumerate_instances("0.0.0.0").await? {
+///     println!("{}\\{} on port {}", instance.server.unwrap_or_default(), instance.instance, instance.port);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn enumerate_instances(host: impl AsRef<str>) -> tiberius::Result<Vec<SqlBrowserInstance>> {
+    use async_std::net::ToSocketAddrs;
+
+    let addr = (host.as_ref(), SQL_BROWSER_PORT)
+        .to_socket_addrs()
+        .await?
+        .next()
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "Could not resolve server host.")
+        })?;
+
+    let buf = send_and_recv(addr, &[0x03]).await?;
+    Ok(parse_response(&buf))
+}
+
+/// Broadcasts an enumeration request to the local subnet and collects the
+/// instances that answer, until one second passes with no further answer.
+pub async fn enumerate_broadcast() -> tiberius::Result<Vec<SqlBrowserInstance>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&[0x02], (std::net::Ipv4Addr::BROADCAST, SQL_BROWSER_PORT)).await?;
+
+    let mut instances = Vec::new();
+    let mut buf = vec![0u8; 4096];
+
+    loop {
+        match io::timeout(RECV_TIMEOUT, socket.recv(&mut buf)).await {
+            Ok(len) => instances.extend(parse_response(&buf[..len])),
+            Err(_) => break,
+        }
+    }
+
+    Ok(instances)
+}
+
+async fn send_and_recv(addr: SocketAddr, msg: &[u8]) -> tiberius::Result<Vec<u8>> {
+    let local_bind: SocketAddr = if addr.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+
+    let mut buf = vec![0u8; 4096];
+
+    let socket = UdpSocket::bind(&local_bind).await?;
+    socket.send_to(msg, &addr).await?;
+
+    let len = io::timeout(RECV_TIMEOUT, socket.recv(&mut buf))
+        .map_err(|_| {
+            tiberius::Error::Conversion("SQL browser timeout while resolving instance".into())
+        })
+        .await?;
+
+    buf.truncate(len);
+    Ok(buf)
+}
+
+/// Parses the SQL Browser response body: a `0x05` header byte, a 2-byte
+/// little-endian payload length, then a semicolon-delimited key/value
+/// string, with individual instance records separated by `;;`.
+fn parse_response(buf: &[u8]) -> Vec<SqlBrowserInstance> {
+    if buf.first() != Some(&0x05) || buf.len() < 3 {
+        return Vec::new();
+    }
+
+    let len = u16::from_le_bytes([buf[1], buf[2]]) as usize;
+    let payload = match buf.get(3..3 + len) {
+        Some(payload) => payload,
+        None => &buf[3..],
+    };
+
+    let payload = match str::from_utf8(payload) {
+        Ok(payload) => payload,
+        Err(_) => return Vec::new(),
+    };
+
+    payload
+        .split(";;")
+        .filter_map(parse_record)
+        .collect()
+}
+
+fn parse_record(record: &str) -> Option<SqlBrowserInstance> {
+    if record.is_empty() {
+        return None;
+    }
+
+    let mut fields = record.split(';');
+    let mut server = None;
+    let mut instance = None;
+    let mut is_clustered = false;
+    let mut version = None;
+    let mut port = None;
+
+    while let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+        match key {
+            "ServerName" => server = Some(value.to_string()),
+            "InstanceName" => instance = Some(value.to_string()),
+            "IsClustered" => is_clustered = value.eq_ignore_ascii_case("yes"),
+            "Version" => version = Some(value.to_string()),
+            "tcp" => port = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    Some(SqlBrowserInstance {
+        server,
+        instance: instance?,
+        port: port?,
+        version,
+        is_clustered,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(payload: &str) -> Vec<u8> {
+        let mut buf = vec![0x05];
+        buf.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        buf.extend_from_slice(payload.as_bytes());
+        buf
+    }
+
+    #[test]
+    fn parses_a_single_instance() {
+        let buf = response(
+            "ServerName;HOST;InstanceName;SQLEXPRESS;IsClustered;No;Version;15.0.2000.5;tcp;1433;;",
+        );
+
+        let instances = parse_response(&buf);
+        assert_eq!(
+            instances,
+            vec![SqlBrowserInstance {
+                server: Some("HOST".into()),
+                instance: "SQLEXPRESS".into(),
+                port: 1433,
+                version: Some("15.0.2000.5".into()),
+                is_clustered: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn missing_record_terminator_still_parses_the_trailing_record() {
+        // No trailing `;;`: `split(";;")` just yields the whole payload as a
+        // single record, which still parses fine.
+        let buf = response("InstanceName;SQLEXPRESS;tcp;1433");
+
+        let instances = parse_response(&buf);
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].instance, "SQLEXPRESS");
+    }
+
+    #[test]
+    fn truncated_length_prefix_falls_back_to_the_rest_of_the_buffer() {
+        // The declared length runs past the end of the buffer; the parser
+        // should use what's actually there instead of panicking on a
+        // out-of-bounds slice.
+        let mut buf = vec![0x05];
+        buf.extend_from_slice(&1000u16.to_le_bytes());
+        buf.extend_from_slice(b"InstanceName;SQLEXPRESS;tcp;1433;;");
+
+        let instances = parse_response(&buf);
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].instance, "SQLEXPRESS");
+    }
+
+    #[test]
+    fn non_utf8_payload_yields_no_instances() {
+        let mut buf = vec![0x05, 0x02, 0x00];
+        buf.extend_from_slice(&[0xff, 0xfe]);
+
+        assert!(parse_response(&buf).is_empty());
+    }
+
+    #[test]
+    fn missing_header_byte_yields_no_instances() {
+        assert!(parse_response(b"garbage").is_empty());
+    }
+
+    #[test]
+    fn too_short_for_a_length_prefix_yields_no_instances() {
+        assert!(parse_response(&[0x05, 0x00]).is_empty());
+    }
+
+    #[test]
+    fn record_missing_required_fields_is_skipped() {
+        // No InstanceName/tcp pair, so the record can't become a
+        // `SqlBrowserInstance`.
+        assert!(parse_record("ServerName;HOST").is_none());
+    }
+
+    #[test]
+    fn empty_record_is_skipped() {
+        assert!(parse_record("").is_none());
+    }
+}
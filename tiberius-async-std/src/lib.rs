@@ -6,12 +6,26 @@
 #![doc(test(attr(allow(unused_extern_crates, unused_variables))))]
 
 use async_std::{io, net::{self, ToSocketAddrs}};
-use std::{borrow::Cow, convert};
+use std::{
+    borrow::Cow,
+    convert,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use futures::future;
 
 use tiberius::ToSql;
 
+mod browser;
+mod pool;
+mod tls;
+
+use tls::{CaTrust, PinnedCertificate};
+
+pub use browser::{enumerate_broadcast, enumerate_instances, SqlBrowserInstance};
+pub use pool::{Pool, PoolBuilder, PooledConnection};
+
 /// `Client` is the main entry point to the SQL Server, providing query
 /// execution capabilities.
 ///
@@ -135,12 +149,79 @@ impl Client {
         self.inner.query(query, params).await
     }
 
+    /// Executes SQL statements, returning every result set produced by the
+    /// query as its own `Vec<Row>`.
+    ///
+    /// Useful for batches of multiple `SELECT` statements (`SELECT ...;
+    /// SELECT ...;`), where [`query`](Self::query) would otherwise require
+    /// manually driving the [`QueryStream`](tiberius::QueryStream) and
+    /// splitting rows by result set.
+    ///
+    /// ```no_run
+    /// # use tiberius_async_std::Client;
+    /// # #[allow(unused)]
+    /// # async fn foo() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let builder = Client::builder();
+    /// # let mut conn = builder.build().await?;
+    /// let results = conn.query_results("SELECT 1; SELECT 2", &[]).await?;
+    /// assert_eq!(2, results.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_results<'a, 'b>(
+        &'a mut self,
+        query: impl Into<Cow<'b, str>>,
+        params: &'b [&'b dyn ToSql],
+    ) -> tiberius::Result<Vec<Vec<tiberius::Row>>>
+    where
+        'a: 'b,
+    {
+        self.inner.query(query, params).await?.into_results().await
+    }
+
+    /// Executes SQL statements, returning only the rows of the first result
+    /// set. Any further result sets produced by the query are discarded.
+    pub async fn query_first<'a, 'b>(
+        &'a mut self,
+        query: impl Into<Cow<'b, str>>,
+        params: &'b [&'b dyn ToSql],
+    ) -> tiberius::Result<Vec<tiberius::Row>>
+    where
+        'a: 'b,
+    {
+        self.inner
+            .query(query, params)
+            .await?
+            .into_first_result()
+            .await
+    }
+
+    /// Executes a parameterless SQL batch, returning every row from every
+    /// result set it produces, flattened into a single `Vec<Row>`.
+    pub async fn simple_query<'a, 'b>(
+        &'a mut self,
+        query: impl Into<Cow<'b, str>>,
+    ) -> tiberius::Result<Vec<tiberius::Row>>
+    where
+        'a: 'b,
+    {
+        let results = self.inner.query(query, &[]).await?.into_results().await?;
+        Ok(results.into_iter().flatten().collect())
+    }
+
     /// Starts an instance of [`ClientBuilder`] for specifying the connect
     /// options.
     ///
     /// [`ClientBuilder`]: struct.ClientBuilder.html
     pub fn builder<'a>() -> ClientBuilder<'a> {
-        tiberius::ClientBuilder::new(Self::new, connector).into()
+        let config = Arc::new(Mutex::new(ConnectorConfig::default()));
+        let connector_config = config.clone();
+
+        let inner = tiberius::ClientBuilder::new(Self::new, move |addr, instance_name| {
+            connector(connector_config.clone(), addr, instance_name)
+        });
+
+        ClientBuilder { inner, config }
     }
 
 }
@@ -148,20 +229,14 @@ impl Client {
 /// A builder for creating a new [`Client`].
 ///
 /// [`Client`]: struct.Client.html
-#[derive(Debug)]
 pub struct ClientBuilder<'a> {
     inner: tiberius::ClientBuilder<'a, net::TcpStream, Client>,
+    config: Arc<Mutex<ConnectorConfig>>,
 }
 
-impl<'a> convert::From<tiberius::ClientBuilder<'a, net::TcpStream, Client>> for ClientBuilder<'a> {
-    fn from(inner: tiberius::ClientBuilder<'a, net::TcpStream, Client>) -> ClientBuilder<'a> {
-        ClientBuilder { inner }
-    }
-}
-
-impl<'a> convert::From<ClientBuilder<'a>> for tiberius::ClientBuilder<'a, net::TcpStream, Client> {
-    fn from(local_builder: ClientBuilder<'a> )-> tiberius::ClientBuilder<'a, net::TcpStream, Client> {
-        local_builder.inner
+impl<'a> std::fmt::Debug for ClientBuilder<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientBuilder").field("inner", &self.inner).finish()
     }
 }
 
@@ -173,8 +248,16 @@ impl<'a> ClientBuilder<'a> {
 
     /// Create a `ClientBuilder` with options specified in the ADO string format
     pub fn from_ado_string(conn_str: &str) -> tiberius::Result<ClientBuilder<'a>> {
-        tiberius::ClientBuilder::from_ado_string(Client::new, connector, conn_str)
-            .map(convert::Into::into)
+        let config = Arc::new(Mutex::new(ConnectorConfig::default()));
+        let connector_config = config.clone();
+
+        let inner = tiberius::ClientBuilder::from_ado_string(
+            Client::new,
+            move |addr, instance_name| connector(connector_config.clone(), addr, instance_name),
+            conn_str,
+        )?;
+
+        Ok(ClientBuilder { inner, config })
     }
 
     /// A host or ip address to connect to.
@@ -198,12 +281,11 @@ impl<'a> ClientBuilder<'a> {
         self.inner.database(database)
     }
 
-    /// The instance name as defined in the SQL Browser. Only available on
-    /// Windows platforms.
+    /// The instance name as defined in the SQL Browser.
     ///
     /// If specified, the port is replaced with the value returned from the
-    /// browser.
-    #[cfg(any(windows, doc))]
+    /// browser. Works on every platform, since resolution only needs a plain
+    /// UDP socket.
     pub fn instance_name(&mut self, name: impl ToString) {
         self.inner.instance_name(name)
     }
@@ -222,68 +304,166 @@ impl<'a> ClientBuilder<'a> {
         self.inner.trust_cert()
     }
 
+    /// Adds one or more PEM-encoded root certificates to the TLS trust
+    /// store, either read from a file or already loaded in memory.
+    ///
+    /// This validates the server certificate against a private or
+    /// self-signed CA, without resorting to [`trust_cert`](Self::trust_cert)
+    /// and disabling validation altogether.
+    ///
+    /// `tiberius`'s own `ClientBuilder` has no hook for a custom trust
+    /// store, so this is enforced by the wrapper: before handing the
+    /// connection to `tiberius`, it dials the server once over TLS to check
+    /// the presented certificate against this CA bundle, and fails the
+    /// connection attempt up front if it doesn't check out. Since that
+    /// pre-flight check already performed the validation `tiberius` would
+    /// otherwise redo against the system trust store (and fail), this also
+    /// calls [`trust_cert`](Self::trust_cert) internally so the real
+    /// handshake doesn't reject the very certificate we just verified.
+    pub fn trust_ca_pem<'p>(&mut self, source: impl Into<CaCertificateSource<'p>>) -> tiberius::Result<()> {
+        let pem = match source.into() {
+            CaCertificateSource::Path(path) => std::fs::read(path)?,
+            CaCertificateSource::Pem(bytes) => bytes.to_vec(),
+        };
+
+        self.config.lock().unwrap().ca_trust = Some(CaTrust::from_pem(pem)?);
+        self.inner.trust_cert();
+
+        Ok(())
+    }
+
+    /// Pins the server certificate by its SHA-256 fingerprint, accepting
+    /// only a server presenting exactly this certificate, regardless of
+    /// which CA issued it.
+    ///
+    /// Like [`trust_ca_pem`](Self::trust_ca_pem), this is enforced by the
+    /// wrapper through a one-off validation handshake, since `tiberius`
+    /// itself has no certificate-pinning hook, and likewise calls
+    /// [`trust_cert`](Self::trust_cert) internally so the real handshake
+    /// relies on our fingerprint check instead of the system trust store.
+    pub fn trust_cert_sha256(&mut self, fingerprint: impl AsRef<[u8]>) -> tiberius::Result<()> {
+        let pinned = PinnedCertificate::from_sha256(fingerprint.as_ref().to_vec())?;
+        self.config.lock().unwrap().pinned_certificate = Some(pinned);
+        self.inner.trust_cert();
+
+        Ok(())
+    }
+
     /// Sets the authentication method.
     pub fn authentication(&mut self, auth: tiberius::AuthMethod) {
         self.inner.authentication(auth)
     }
+
+    /// Runs a hook on the connected TCP stream before the TDS handshake
+    /// starts, letting you tune socket options that still take effect after
+    /// `connect` has succeeded (e.g. keepalive, nodelay) without forking the
+    /// connector. Options that must be set before bind/connect, such as
+    /// `SO_REUSEADDR` or a custom bind address, cannot be applied here.
+    pub fn with_tcp_stream_modifier<F>(&mut self, modifier: F)
+    where
+        F: Fn(&net::TcpStream) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.config.lock().unwrap().tcp_stream_modifier = Some(Arc::new(modifier));
+    }
+
+    /// The maximum time to wait for the TCP connection to be established.
+    ///
+    /// - Defaults to waiting forever.
+    pub fn connect_timeout(&mut self, timeout: Duration) {
+        self.config.lock().unwrap().connect_timeout = Some(timeout);
+    }
+}
+
+/// Where to read PEM-encoded root certificate data from for
+/// [`ClientBuilder::trust_ca_pem`].
+#[derive(Debug, Clone, Copy)]
+pub enum CaCertificateSource<'a> {
+    /// Read the PEM data from this file path.
+    Path(&'a std::path::Path),
+    /// Already-loaded PEM bytes.
+    Pem(&'a [u8]),
+}
+
+impl<'a> convert::From<&'a std::path::Path> for CaCertificateSource<'a> {
+    fn from(path: &'a std::path::Path) -> Self {
+        CaCertificateSource::Path(path)
+    }
 }
 
+impl<'a> convert::From<&'a [u8]> for CaCertificateSource<'a> {
+    fn from(pem: &'a [u8]) -> Self {
+        CaCertificateSource::Pem(pem)
+    }
+}
+
+#[derive(Default)]
+struct ConnectorConfig {
+    tcp_stream_modifier: Option<Arc<dyn Fn(&net::TcpStream) -> io::Result<()> + Send + Sync>>,
+    connect_timeout: Option<Duration>,
+    ca_trust: Option<CaTrust>,
+    pinned_certificate: Option<PinnedCertificate>,
+}
 
-fn connector<'a>(addr: String, instance_name: Option<String>) -> future::BoxFuture<'a, tiberius::Result<net::TcpStream>>
-{
+fn connector<'a>(
+    config: Arc<Mutex<ConnectorConfig>>,
+    addr: String,
+    instance_name: Option<String>,
+) -> future::BoxFuture<'a, tiberius::Result<net::TcpStream>> {
     let stream = async move {
-        let mut addr = addr.to_socket_addrs().await?.next().ok_or_else(|| {
+        let host = addr
+            .rsplit_once(':')
+            .map(|(host, _)| host.to_string())
+            .unwrap_or_else(|| addr.clone());
+
+        let mut resolved = addr.to_socket_addrs().await?.next().ok_or_else(|| {
             io::Error::new(io::ErrorKind::NotFound, "Could not resolve server host.")
         })?;
 
         if let Some(ref instance_name) = instance_name {
-            addr = find_tcp_port(addr, instance_name).await?;
+            resolved = browser::resolve_instance_port(resolved, instance_name).await?;
         };
 
-        let stream = net::TcpStream::connect(addr).await?;
-        stream.set_nodelay(true)?;
-        Ok(stream)
-    };
-    Box::pin(stream)
-}
+        let (connect_timeout, tcp_stream_modifier, ca_trust, pinned_certificate) = {
+            let config = config.lock().unwrap();
+            (
+                config.connect_timeout,
+                config.tcp_stream_modifier.clone(),
+                config.ca_trust.clone(),
+                config.pinned_certificate.clone(),
+            )
+        };
 
-#[cfg(not(windows))]
-async fn find_tcp_port(addr: std::net::SocketAddr, _: &str) -> tiberius::Result<std::net::SocketAddr> {
-    Ok(addr)
-}
+        if ca_trust.is_some() || pinned_certificate.is_some() {
+            let validation_connect = net::TcpStream::connect(resolved);
 
-#[cfg(windows)]
-async fn find_tcp_port(addr: std::net::SocketAddr, instance_name: &str) -> tiberius::Result<std::net::SocketAddr> {
-    use std::{time, str};
-    use futures::TryFutureExt;
-    // First resolve the instance to a port via the
-    // SSRP protocol/MS-SQLR protocol [1]
-    // [1] https://msdn.microsoft.com/en-us/library/cc219703.aspx
-
-    let local_bind: std::net::SocketAddr = if addr.is_ipv4() {
-        "0.0.0.0:0".parse().unwrap()
-    } else {
-        "[::]:0".parse().unwrap()
-    };
+            let validation_stream = match connect_timeout {
+                Some(duration) => io::timeout(duration, validation_connect).await?,
+                None => validation_connect.await?,
+            };
 
-    let msg = [&[4u8], instance_name.as_bytes()].concat();
-    let mut buf = vec![0u8; 4096];
+            tls::verify_server_certificate(
+                &host,
+                validation_stream,
+                ca_trust.as_ref(),
+                pinned_certificate.as_ref(),
+            )
+            .await?;
+        }
 
-    let socket = net::UdpSocket::bind(&local_bind).await?;
-    socket.send_to(&msg, &addr).await?;
+        let connect = net::TcpStream::connect(resolved);
 
-    let timeout = time::Duration::from_millis(1000);
+        let stream = match connect_timeout {
+            Some(duration) => io::timeout(duration, connect).await?,
+            None => connect.await?,
+        };
 
-    let len = io::timeout(timeout, socket.recv(&mut buf))
-        .map_err(|_| {
-            tiberius::Error::Conversion(
-                format!(
-                    "SQL browser timeout during resolving instance {}",
-                    instance_name
-                )
-                .into(),
-            )
-        }).await?;
+        stream.set_nodelay(true)?;
+
+        if let Some(modifier) = tcp_stream_modifier {
+            modifier(&stream)?;
+        }
 
-    tiberius::consume_sql_browser_message(addr, buf, len, instance_name)
+        Ok(stream)
+    };
+    Box::pin(stream)
 }
\ No newline at end of file
@@ -0,0 +1,526 @@
+//! A connection pool for [`Client`], so a server can share a bounded number
+//! of live TDS connections across many tasks instead of opening a fresh one
+//! per request.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_std::{
+    channel::{self, Receiver, Sender},
+    future::timeout,
+    sync::Mutex,
+    task,
+};
+use futures::future::BoxFuture;
+
+use crate::Client;
+
+type Connect = dyn Fn() -> BoxFuture<'static, tiberius::Result<Client>> + Send + Sync;
+
+struct Idle {
+    client: Client,
+    created_at: Instant,
+    idle_since: Instant,
+}
+
+struct State {
+    idle: VecDeque<Idle>,
+}
+
+struct Shared {
+    connect: Box<Connect>,
+    state: Mutex<State>,
+    total: AtomicUsize,
+    notify_tx: Sender<()>,
+    notify_rx: Receiver<()>,
+    max_size: usize,
+    max_idle_duration: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    acquire_timeout: Option<Duration>,
+}
+
+/// Tracks a single slot out of [`Shared::max_size`] that is either backing a
+/// freshly created connection or an idle one popped off the queue.
+///
+/// Dropping the guard without calling [`Reservation::disarm`] releases the
+/// slot, which is what happens if the future holding it is cancelled (e.g.
+/// on a [`PoolBuilder::acquire_timeout`] firing)
+/// instead of running to completion — keeping `total` accurate even when
+/// the connect/validate step never gets to return.
+struct Reservation {
+    shared: Arc<Shared>,
+    armed: bool,
+}
+
+impl Reservation {
+    /// Guards a brand new slot whose increment of `total` was already made
+    /// by the caller. Only call this once a slot has actually been claimed
+    /// (e.g. via [`Pool::try_reserve_new_slot`]).
+    fn new(shared: Arc<Shared>) -> Self {
+        Reservation {
+            shared,
+            armed: true,
+        }
+    }
+
+    /// Takes over an already-counted slot, e.g. one backing an idle
+    /// connection popped off the queue.
+    fn reuse(shared: Arc<Shared>) -> Self {
+        Reservation {
+            shared,
+            armed: true,
+        }
+    }
+
+    /// Commits the slot to a connection the caller is about to hand out,
+    /// so dropping the guard no longer releases it.
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.armed {
+            self.shared.total.fetch_sub(1, Ordering::SeqCst);
+            let _ = self.shared.notify_tx.try_send(());
+        }
+    }
+}
+
+/// A pool of [`Client`] connections, built with [`Pool::builder`].
+///
+/// Cloning a `Pool` is cheap; every clone shares the same underlying set of
+/// connections.
+///
+/// ```no_run
+/// # use tiberius_async_std::{Client, Pool};
+/// # use tiberius::AuthMethod;
+/// # #[allow(unused)]
+/// # async fn foo() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut builder = Pool::builder(|| {
+///     Box::pin(async {
+///         let mut builder = Client::builder();
+///         builder.host("0.0.0.0");
+///         builder.port(1433);
+///         builder.authentication(AuthMethod::sql_server("SA", "<Mys3cureP4ssW0rD>"));
+///         builder.build().await
+///     })
+/// });
+///
+/// builder.max_size(10);
+/// builder.min_idle(1);
+///
+/// let pool = builder.build().await?;
+/// let mut conn = pool.get().await?;
+/// let _ = conn.query("SELECT 1", &[]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool").finish()
+    }
+}
+
+impl Pool {
+    /// Starts a [`PoolBuilder`] for configuring a new `Pool`.
+    ///
+    /// `connect` is called every time the pool needs a brand new connection.
+    /// It typically wraps a [`ClientBuilder`](crate::ClientBuilder) already
+    /// configured with the host, credentials and TLS options to use.
+    pub fn builder<F>(connect: F) -> PoolBuilder
+    where
+        F: Fn() -> BoxFuture<'static, tiberius::Result<Client>> + Send + Sync + 'static,
+    {
+        PoolBuilder {
+            connect: Box::new(connect),
+            max_size: 10,
+            min_idle: 0,
+            max_idle_duration: None,
+            max_lifetime: None,
+            acquire_timeout: None,
+        }
+    }
+
+    /// Checks out a connection from the pool, waiting for a free slot if
+    /// every connection is currently in use.
+    ///
+    /// If the pool was built with [`PoolBuilder::acquire_timeout`] and no
+    /// slot becomes available in time, returns a
+    /// [`tiberius::Error::Conversion`] describing the timeout.
+    pub async fn get(&self) -> tiberius::Result<PooledConnection> {
+        let acquire = self.acquire();
+
+        match self.shared.acquire_timeout {
+            Some(duration) => timeout(duration, acquire).await.map_err(|_| {
+                tiberius::Error::Conversion(
+                    format!("timed out after {:?} waiting for a pooled connection", duration)
+                        .into(),
+                )
+            })?,
+            None => acquire.await,
+        }
+    }
+
+    async fn acquire(&self) -> tiberius::Result<PooledConnection> {
+        loop {
+            let idle = {
+                let mut state = self.shared.state.lock().await;
+                state.idle.pop_front()
+            };
+
+            if let Some(idle) = idle {
+                let reservation = Reservation::reuse(self.shared.clone());
+
+                if self.is_expired(&idle) {
+                    // Dropping the reservation here releases the slot.
+                    continue;
+                }
+
+                let mut client = idle.client;
+
+                if Self::is_alive(&mut client).await {
+                    reservation.disarm();
+
+                    return Ok(PooledConnection {
+                        client: Some(client),
+                        created_at: idle.created_at,
+                        pool: self.clone(),
+                    });
+                }
+
+                // Dead connection: drop the reservation and try again.
+                continue;
+            }
+
+            if let Some(reservation) = self.try_reserve_new_slot() {
+                return match (self.shared.connect)().await {
+                    Ok(client) => {
+                        reservation.disarm();
+
+                        Ok(PooledConnection {
+                            client: Some(client),
+                            created_at: Instant::now(),
+                            pool: self.clone(),
+                        })
+                    }
+                    // Dropping the reservation here releases the slot, also
+                    // covering the case where this future is cancelled
+                    // while `connect` is still pending.
+                    Err(e) => Err(e),
+                };
+            }
+
+            let _ = self.shared.notify_rx.recv().await;
+        }
+    }
+
+    /// Atomically claims a new slot if the pool has not yet reached
+    /// [`PoolBuilder::max_size`], returning a [`Reservation`] guarding it.
+    fn try_reserve_new_slot(&self) -> Option<Reservation> {
+        let claimed = self
+            .shared
+            .total
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |total| {
+                if total < self.shared.max_size {
+                    Some(total + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok();
+
+        claimed.then(|| Reservation::new(self.shared.clone()))
+    }
+
+    fn is_expired(&self, idle: &Idle) -> bool {
+        let idle_expired = self
+            .shared
+            .max_idle_duration
+            .map_or(false, |max| idle.idle_since.elapsed() > max);
+
+        let lifetime_expired = self
+            .shared
+            .max_lifetime
+            .map_or(false, |max| idle.created_at.elapsed() > max);
+
+        idle_expired || lifetime_expired
+    }
+
+    async fn is_alive(client: &mut Client) -> bool {
+        client.simple_query("SELECT 1").await.is_ok()
+    }
+
+    async fn release(&self, client: Client, created_at: Instant) {
+        let lifetime_expired = self
+            .shared
+            .max_lifetime
+            .map_or(false, |max| created_at.elapsed() > max);
+
+        if lifetime_expired {
+            self.shared.total.fetch_sub(1, Ordering::SeqCst);
+        } else {
+            let mut state = self.shared.state.lock().await;
+
+            state.idle.push_back(Idle {
+                client,
+                created_at,
+                idle_since: Instant::now(),
+            });
+        }
+
+        let _ = self.shared.notify_tx.try_send(());
+    }
+}
+
+/// A builder for creating a new [`Pool`].
+pub struct PoolBuilder {
+    connect: Box<Connect>,
+    max_size: usize,
+    min_idle: usize,
+    max_idle_duration: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    acquire_timeout: Option<Duration>,
+}
+
+impl fmt::Debug for PoolBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolBuilder")
+            .field("max_size", &self.max_size)
+            .field("min_idle", &self.min_idle)
+            .field("max_idle_duration", &self.max_idle_duration)
+            .field("max_lifetime", &self.max_lifetime)
+            .field("acquire_timeout", &self.acquire_timeout)
+            .finish()
+    }
+}
+
+impl PoolBuilder {
+    /// The maximum number of connections the pool will open at once.
+    ///
+    /// - Defaults to `10`.
+    pub fn max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+    }
+
+    /// The number of connections opened eagerly when the pool is built and
+    /// left idle for the first [`Pool::get`] calls. The pool does not open
+    /// further connections later to keep this many idle.
+    ///
+    /// Must not be greater than [`PoolBuilder::max_size`]; [`PoolBuilder::build`]
+    /// returns an error otherwise.
+    ///
+    /// - Defaults to `0`.
+    pub fn min_idle(&mut self, min_idle: usize) {
+        self.min_idle = min_idle;
+    }
+
+    /// The maximum time a connection may sit idle before it is closed
+    /// instead of being reused.
+    ///
+    /// - Defaults to no limit.
+    pub fn max_idle_duration(&mut self, duration: Duration) {
+        self.max_idle_duration = Some(duration);
+    }
+
+    /// The maximum total lifetime of a connection, counted from when it was
+    /// opened, after which it is closed instead of being reused.
+    ///
+    /// - Defaults to no limit.
+    pub fn max_lifetime(&mut self, duration: Duration) {
+        self.max_lifetime = Some(duration);
+    }
+
+    /// The maximum time [`Pool::get`] will wait for a free slot before
+    /// giving up.
+    ///
+    /// - Defaults to waiting forever.
+    pub fn acquire_timeout(&mut self, duration: Duration) {
+        self.acquire_timeout = Some(duration);
+    }
+
+    /// Builds the [`Pool`], eagerly opening [`PoolBuilder::min_idle`]
+    /// connections.
+    ///
+    /// Returns a [`tiberius::Error::Conversion`] if `min_idle` is greater
+    /// than `max_size`.
+    pub async fn build(self) -> tiberius::Result<Pool> {
+        if self.min_idle > self.max_size {
+            return Err(tiberius::Error::Conversion(
+                format!(
+                    "min_idle ({}) cannot be greater than max_size ({})",
+                    self.min_idle, self.max_size
+                )
+                .into(),
+            ));
+        }
+
+        let (notify_tx, notify_rx) = channel::unbounded();
+
+        let shared = Arc::new(Shared {
+            connect: self.connect,
+            state: Mutex::new(State {
+                idle: VecDeque::new(),
+            }),
+            total: AtomicUsize::new(0),
+            notify_tx,
+            notify_rx,
+            max_size: self.max_size,
+            max_idle_duration: self.max_idle_duration,
+            max_lifetime: self.max_lifetime,
+            acquire_timeout: self.acquire_timeout,
+        });
+
+        let pool = Pool { shared };
+
+        for _ in 0..self.min_idle {
+            let client = (pool.shared.connect)().await?;
+            pool.shared.total.fetch_add(1, Ordering::SeqCst);
+
+            let mut state = pool.shared.state.lock().await;
+
+            state.idle.push_back(Idle {
+                client,
+                created_at: Instant::now(),
+                idle_since: Instant::now(),
+            });
+        }
+
+        Ok(pool)
+    }
+}
+
+/// An RAII guard around a pooled [`Client`], returned from [`Pool::get`].
+///
+/// The underlying connection is returned to the pool when the guard is
+/// dropped, so it can be reused by the next [`Pool::get`] call.
+pub struct PooledConnection {
+    client: Option<Client>,
+    created_at: Instant,
+    pool: Pool,
+}
+
+impl fmt::Debug for PooledConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledConnection").finish()
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().expect("connection taken before drop")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().expect("connection taken before drop")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            let pool = self.pool.clone();
+            let created_at = self.created_at;
+
+            task::spawn(async move {
+                pool.release(client, created_at).await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shared(max_size: usize) -> Arc<Shared> {
+        let (notify_tx, notify_rx) = channel::unbounded();
+
+        Arc::new(Shared {
+            connect: Box::new(|| unreachable!("connect should not be called by these tests")),
+            state: Mutex::new(State {
+                idle: VecDeque::new(),
+            }),
+            total: AtomicUsize::new(0),
+            notify_tx,
+            notify_rx,
+            max_size,
+            max_idle_duration: None,
+            max_lifetime: None,
+            acquire_timeout: None,
+        })
+    }
+
+    #[test]
+    fn try_reserve_new_slot_respects_max_size() {
+        let pool = Pool { shared: shared(1) };
+
+        let first = pool.try_reserve_new_slot();
+        assert!(first.is_some());
+        assert_eq!(pool.shared.total.load(Ordering::SeqCst), 1);
+
+        let second = pool.try_reserve_new_slot();
+        assert!(second.is_none(), "slot should not be granted past max_size");
+    }
+
+    #[test]
+    fn dropping_an_armed_reservation_releases_the_slot() {
+        // Mirrors what happens when the future holding a freshly reserved
+        // slot is cancelled (e.g. `acquire_timeout` firing) before it ever
+        // disarms the reservation: the slot must go back to the pool
+        // instead of leaking forever.
+        let pool = Pool { shared: shared(1) };
+
+        let reservation = pool.try_reserve_new_slot().expect("slot available");
+        assert_eq!(pool.shared.total.load(Ordering::SeqCst), 1);
+
+        drop(reservation);
+        assert_eq!(pool.shared.total.load(Ordering::SeqCst), 0);
+
+        // The freed slot must be reusable afterwards.
+        assert!(pool.try_reserve_new_slot().is_some());
+    }
+
+    #[test]
+    fn disarming_a_reservation_keeps_the_slot_reserved() {
+        let pool = Pool { shared: shared(1) };
+
+        let reservation = pool.try_reserve_new_slot().expect("slot available");
+        reservation.disarm();
+        assert_eq!(pool.shared.total.load(Ordering::SeqCst), 1);
+
+        assert!(pool.try_reserve_new_slot().is_none());
+    }
+
+    #[async_std::test]
+    async fn min_idle_greater_than_max_size_is_rejected() {
+        let builder = PoolBuilder {
+            connect: Box::new(|| unreachable!("connect should not be called")),
+            max_size: 1,
+            min_idle: 2,
+            max_idle_duration: None,
+            max_lifetime: None,
+            acquire_timeout: None,
+        };
+
+        assert!(builder.build().await.is_err());
+    }
+}
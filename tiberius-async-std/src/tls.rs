@@ -0,0 +1,126 @@
+//! Custom root CA trust and certificate pinning for [`ClientBuilder`].
+//!
+//! `tiberius`'s own `ClientBuilder` only exposes `encryption` and
+//! `trust_cert` (accept-everything) for TLS configuration, with no hook for
+//! a custom trust store or a pinned fingerprint. Since the wire-level TLS
+//! handshake happens inside `tiberius::Client::connect` itself, this module
+//! cannot plug into that handshake directly. Instead it performs a one-off
+//! validation handshake against the configured CA bundle and/or pinned
+//! fingerprint *before* handing the real connection over to `tiberius`,
+//! rejecting the connection attempt up front if the server's certificate
+//! doesn't check out.
+//!
+//! [`ClientBuilder`]: crate::ClientBuilder
+
+use async_native_tls::{Certificate, TlsConnector};
+use async_std::net::TcpStream;
+use sha2::{Digest, Sha256};
+
+/// A bundle of one or more PEM-encoded root certificates.
+#[derive(Debug, Clone)]
+pub(crate) struct CaTrust {
+    pem: Vec<u8>,
+}
+
+impl CaTrust {
+    /// Parses `pem`, rejecting it up front if it doesn't contain at least
+    /// one well-formed PEM certificate block.
+    pub(crate) fn from_pem(pem: Vec<u8>) -> tiberius::Result<Self> {
+        Certificate::from_pem(&pem)
+            .map_err(|e| tiberius::Error::Conversion(format!("invalid CA certificate: {}", e).into()))?;
+
+        Ok(CaTrust { pem })
+    }
+}
+
+/// A pinned server certificate, identified by its SHA-256 fingerprint.
+#[derive(Debug, Clone)]
+pub(crate) struct PinnedCertificate {
+    sha256: Vec<u8>,
+}
+
+impl PinnedCertificate {
+    /// Validates that `sha256` is a 32-byte digest before storing it.
+    pub(crate) fn from_sha256(sha256: Vec<u8>) -> tiberius::Result<Self> {
+        if sha256.len() != 32 {
+            return Err(tiberius::Error::Conversion(
+                format!(
+                    "trust_cert_sha256 expects a 32-byte SHA-256 digest, got {} bytes",
+                    sha256.len()
+                )
+                .into(),
+            ));
+        }
+
+        Ok(PinnedCertificate { sha256 })
+    }
+}
+
+/// Dials `host` over TLS on `stream`, purely to validate the certificate
+/// the server presents against `ca` and/or `pinned`. The TLS session is
+/// torn down once validated; the real, TDS-level encrypted session is
+/// negotiated separately by `tiberius::Client::connect` on a fresh
+/// connection.
+pub(crate) async fn verify_server_certificate(
+    host: &str,
+    stream: TcpStream,
+    ca: Option<&CaTrust>,
+    pinned: Option<&PinnedCertificate>,
+) -> tiberius::Result<()> {
+    let mut connector = TlsConnector::new();
+
+    if let Some(ca) = ca {
+        let pem = std::str::from_utf8(&ca.pem)
+            .map_err(|e| tiberius::Error::Conversion(format!("invalid CA certificate: {}", e).into()))?;
+
+        // `Certificate::from_pem` only reads the first certificate, so add
+        // every block of the bundle one by one.
+        for block in pem
+            .split_inclusive("-----END CERTIFICATE-----")
+            .filter(|block| block.contains("-----BEGIN CERTIFICATE-----"))
+        {
+            let cert = Certificate::from_pem(block.as_bytes())
+                .map_err(|e| tiberius::Error::Conversion(format!("invalid CA certificate: {}", e).into()))?;
+
+            connector = connector.add_root_certificate(cert);
+        }
+    }
+
+    if pinned.is_some() {
+        // System/CA validation, hostname included, is irrelevant once we're
+        // pinning to one exact certificate; we do our own comparison below
+        // instead. CA-only configuration keeps both checks enabled.
+        connector = connector
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+
+    let tls_stream = connector.connect(host, stream).await.map_err(|e| {
+        tiberius::Error::Conversion(format!("TLS certificate validation failed: {}", e).into())
+    })?;
+
+    if let Some(pinned) = pinned {
+        let peer = tls_stream
+            .peer_certificate()
+            .map_err(|e| {
+                tiberius::Error::Conversion(format!("could not read peer certificate: {}", e).into())
+            })?
+            .ok_or_else(|| {
+                tiberius::Error::Conversion("server presented no certificate".into())
+            })?;
+
+        let der = peer.to_der().map_err(|e| {
+            tiberius::Error::Conversion(format!("could not encode peer certificate: {}", e).into())
+        })?;
+
+        let digest = Sha256::digest(&der);
+
+        if digest.as_slice() != pinned.sha256.as_slice() {
+            return Err(tiberius::Error::Conversion(
+                "server certificate does not match the pinned SHA-256 fingerprint".into(),
+            ));
+        }
+    }
+
+    Ok(())
+}